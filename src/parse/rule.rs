@@ -35,9 +35,10 @@
 //! ```
 //! # #[macro_use] extern crate abnf;
 //! # use abnf::{Async, BytesMut, Poll};
-//! # use abnf::parse::rule::group;
+//! # use abnf::parse::rule::{group, FatalError};
 //! # struct Res;
 //! # struct E;
+//! # impl FatalError for E { }
 //! # fn rule1(buf: &mut BytesMut) -> Poll<Res, E> { Ok(Async::Ready(Res)) }
 //! # fn rule2(buf: &mut BytesMut) -> Poll<Res, E> { Ok(Async::Ready(Res)) }
 //! fn concat(buf: &mut BytesMut) -> Poll<Res, E> {
@@ -53,11 +54,11 @@
 //!
 //! # Alternatives: `Rule1 / Rule2`
 //!
-//! Alternatives can be parses as a sequence of expressions producing an
+//! Alternatives can be parsed as a sequence of expressions producing an
 //! optional result. The `try_opt!()` macro helps you with that: It returns
 //! early on some result, not ready, or error. Make sure the inner expressions
 //! rewind correctly.
-//! 
+//!
 //! ```
 //! # #[macro_use] extern crate abnf;
 //! # use abnf::{Async, BytesMut, Poll};
@@ -79,7 +80,46 @@
 //! }
 //! # fn main() { }
 //! ```
-//! 
+//!
+//! Writing this out by hand gets old fast. The `alternatives()` function
+//! (and the `alternatives!()` macro, for when the branches aren’t all the
+//! same closure type) do the same thing for you: they try each branch in
+//! turn, returning the first `Ready(Some(_))`.
+//!
+//! If every branch fails, they return the error of whichever branch had
+//! consumed the most input at the point it failed, rather than simply the
+//! last one tried. Note that this only discriminates between branches that
+//! are *not* themselves wrapped in their own `group()` for a recoverable
+//! error: `alternatives()` already resets the buffer to its own starting
+//! point before trying each branch and again if all of them fail, so a
+//! branch is free to leave the buffer partially drained when it returns a
+//! recoverable `Err` — it does not need to rewind itself. A branch that
+//! does rewind itself on a recoverable error (the usual `group()` idiom)
+//! will always measure as zero progress here, collapsing the ranking to
+//! “whichever error was seen first”. A `commit()`-ed, fatal error is
+//! unaffected either way, since it is returned immediately without
+//! entering this ranking at all.
+//!
+//! ```
+//! # #[macro_use] extern crate abnf;
+//! # use abnf::{Async, BytesMut, Poll};
+//! # use abnf::parse::rule::{alternatives, FatalError};
+//! # struct Res;
+//! # struct E;
+//! # impl FatalError for E { }
+//! fn rule1(buf: &mut BytesMut) -> Poll<Option<Res>, E> {
+//!     unimplemented!()
+//! }
+//!
+//! fn rule2(buf: &mut BytesMut) -> Poll<Option<Res>, E> {
+//!     unimplemented!()
+//! }
+//!
+//! fn alt(buf: &mut BytesMut) -> Poll<Option<Res>, E> {
+//!     alternatives(buf, &[rule1, rule2])
+//! }
+//! # fn main() { }
+//! ```
 //!
 //! # Optional Repetition: `*Rule`
 //!
@@ -96,9 +136,10 @@
 //! ```
 //! # #[macro_use] extern crate abnf;
 //! # use abnf::{Async, BytesMut, Poll};
-//! # use abnf::parse::rule::{group, repeat};
+//! # use abnf::parse::rule::{group, repeat, FatalError};
 //! # struct Res;
 //! # struct E;
+//! # impl FatalError for E { }
 //! # fn rule(buf: &mut BytesMut) -> Poll<Res, E> { Ok(Async::Ready(Res)) }
 //! fn repeat_rule(buf: &mut BytesMut) -> Poll<Vec<Res>, E> {
 //!     let mut res = Vec::new();
@@ -116,39 +157,92 @@
 //! # fn main() { }
 //! ```
 //!
+//! `rule()` here is required to drain the buffer whenever it succeeds. If
+//! it doesn’t — e.g., because it matched the empty string — `repeat()`
+//! won’t call it again and again forever; it stops the repetition right
+//! there instead, returning `S::default()` rather than whatever `combine`
+//! would have produced. Because of this, `S` should be a pure sentinel
+//! type — `()`, as above — with the actual result accumulated in a
+//! variable the `combine` closure captures, exactly like `res` is here.
+//! If you need `combine` itself to produce the final value on this path,
+//! use `repeat_range()` with `max: None` instead: it guards against the
+//! same zero-width match, but rather than inventing a result for you, it
+//! calls `combine` with `None` — exactly as it does once `max` elements
+//! have been parsed — so `combine` gets to produce the final value
+//! itself.
+//!
 //! # Specific and Limited Repititions: `<n>Rule` and `<a>*<b>Rule`
 //!
-//! Both of these happen relatively rarely on a rule-level, so there are no
-//! special functions for them. Instead, you can use `repeat()` and pass a
-//! counter into the `combine` closure.
+//! These are handled by `repeat_range()`, which is `repeat()` with a `min`
+//! and an optional `max` count enforced for you. Rather than being handed
+//! the raw result of `parse` forever, `combine` is given `None` once `max`
+//! elements (if any) have successfully been parsed, signalling that no
+//! further attempt will be made — this is exactly like the `Err` case
+//! already means “`parse` itself has run out of matches”, and `combine` is
+//! expected to react to it the same way: by producing a final result. If,
+//! by that point, fewer than `min` elements were successfully parsed, the
+//! whole thing fails with the error produced by `too_few` instead, and the
+//! group is rewound. `repeat()` and `at_least_once()` are the `0*` and
+//! `1*` special cases of this.
 //!
-//! For instance, `6rule` could be implemented like so:
+//! Note that this `min` check only ever runs once `combine` has returned
+//! `Ready` — so `combine` must not itself bail out with `Err` the moment
+//! `parse` fails (`Some(Err(_))`), since that would short-circuit the
+//! whole repetition with the raw parse error before `too_few` gets a
+//! chance to fire. Treat `Some(Err(_))` as “no more elements”, the same
+//! way `None` is, and let `too_few` be what actually enforces `min`.
+//!
+//! For `<n>Rule`, use the `exactly()` wrapper, which just calls
+//! `repeat_range()` with `min == max == n`:
 //!
 //! ```
 //! # #[macro_use] extern crate abnf;
 //! # use abnf::{Async, BytesMut, Poll};
-//! # use abnf::parse::rule::{group, repeat};
+//! # use abnf::parse::rule::{exactly, FatalError};
 //! # struct Res;
 //! # struct E;
+//! # impl FatalError for E { }
 //! # fn rule(buf: &mut BytesMut) -> Poll<Res, E> { Ok(Async::Ready(Res)) }
 //! fn six_rule(buf: &mut BytesMut) -> Poll<Vec<Res>, E> {
 //!     let mut res = Vec::new();
-//!     let mut count = 0;
-//!     try_ready!(repeat(buf, rule, |item| {
-//!         count += 1;
+//!     try_ready!(exactly(buf, 6, rule, |item| {
 //!         match item {
-//!             Ok(item) => {
+//!             Some(Ok(item)) => {
 //!                 res.push(item);
-//!                 if count == 6 {
-//!                     Ok(Async::Ready(()))
-//!                 }
-//!                 else {
-//!                     Ok(Async::NotReady)
-//!                 }
+//!                 Ok(Async::NotReady)
 //!             }
-//!             Err(err) => Err(err)
+//!             Some(Err(_)) => Ok(Async::Ready(())),
+//!             None => Ok(Async::Ready(()))
 //!         }
-//!     }));
+//!     }, || E));
+//!     Ok(Async::Ready(res))
+//! }
+//! # fn main() { }
+//! ```
+//!
+//! And `<a>*<b>Rule` simply passes both bounds along to `repeat_range()`
+//! directly:
+//!
+//! ```
+//! # #[macro_use] extern crate abnf;
+//! # use abnf::{Async, BytesMut, Poll};
+//! # use abnf::parse::rule::{repeat_range, FatalError};
+//! # struct Res;
+//! # struct E;
+//! # impl FatalError for E { }
+//! # fn rule(buf: &mut BytesMut) -> Poll<Res, E> { Ok(Async::Ready(Res)) }
+//! fn two_to_four_rule(buf: &mut BytesMut) -> Poll<Vec<Res>, E> {
+//!     let mut res = Vec::new();
+//!     try_ready!(repeat_range(buf, 2, Some(4), rule, |item| {
+//!         match item {
+//!             Some(Ok(item)) => {
+//!                 res.push(item);
+//!                 Ok(Async::NotReady)
+//!             }
+//!             Some(Err(_)) => Ok(Async::Ready(())),
+//!             None => Ok(Async::Ready(()))
+//!         }
+//!     }, || E));
 //!     Ok(Async::Ready(res))
 //! }
 //! # fn main() { }
@@ -165,9 +259,11 @@
 //! ```
 //! # #[macro_use] extern crate abnf;
 //! # use abnf::{Async, BytesMut, Poll};
-//! # use abnf::parse::rule::{group, at_least_once};
+//! # use abnf::parse::rule::{group, at_least_once, FatalError};
 //! # struct Res;
+//! # #[derive(Default)]
 //! # struct E;
+//! # impl FatalError for E { }
 //! # fn rule(buf: &mut BytesMut) -> Poll<Res, E> { Ok(Async::Ready(Res)) }
 //! fn rule_at_least_once(buf: &mut BytesMut) -> Poll<Vec<Res>, E> {
 //!     let mut res = Vec::new();
@@ -200,9 +296,10 @@
 //! ```
 //! # #[macro_use] extern crate abnf;
 //! # use abnf::{Async, BytesMut, Poll};
-//! # use abnf::parse::rule::{group, optional};
+//! # use abnf::parse::rule::{group, optional, FatalError};
 //! # struct Res1; struct Res2;
 //! # struct E;
+//! # impl FatalError for E { }
 //! # fn rule1(buf: &mut BytesMut) -> Poll<Res1, E> { Ok(Async::Ready(Res1)) }
 //! # fn rule2(buf: &mut BytesMut) -> Poll<Res2, E> { Ok(Async::Ready(Res2)) }
 //! fn rule1_opt_rule2(buf: &mut BytesMut) -> Poll<(Res1, Option<Res2>), E> {
@@ -214,6 +311,129 @@
 //! }
 //! # fn main() { }
 //! ```
+//!
+//!
+//! # Capturing the Matched Bytes
+//!
+//! Sometimes you don’t care about the parsed value at all but about the
+//! raw bytes a rule matched — to re-emit them, hash them, or hand them off
+//! to some other parser later. The `recognize()` function gives you that:
+//! it runs a parsing closure as normal but, on success, returns the slice
+//! of the buffer the closure consumed instead of the closure’s result.
+//!
+//! ```
+//! # #[macro_use] extern crate abnf;
+//! # use abnf::{Async, BytesMut, Poll};
+//! # use abnf::parse::rule::{recognize, FatalError};
+//! # struct Res;
+//! # struct E;
+//! # impl FatalError for E { }
+//! # fn rule(buf: &mut BytesMut) -> Poll<Res, E> { Ok(Async::Ready(Res)) }
+//! fn matched_bytes(buf: &mut BytesMut) -> Poll<BytesMut, E> {
+//!     recognize(buf, rule)
+//! }
+//! # fn main() { }
+//! ```
+//!
+//!
+//! # Separated Lists: `element *("," element)`
+//!
+//! This common shape — one or more elements with a separator in between —
+//! is what `separated()` is for. It parses one `parse_item`, then keeps
+//! alternating `parse_sep` and `parse_item`, feeding each item to `combine`
+//! exactly like `repeat()` does. A separator that isn’t followed by
+//! another item is not an error: it is simply not consumed, and the list
+//! ends there. And, like `repeat()`, a separator/item pair that matches
+//! without draining the buffer stops the list right there rather than
+//! looping forever.
+//!
+//! If the very first `parse_item` fails, its error goes to `combine` as
+//! `Err(_)` like any other, so `separated()` does not by itself insist on
+//! at least one element. The example below does that in `combine`, by
+//! failing if nothing has been collected yet.
+//!
+//! ```
+//! # #[macro_use] extern crate abnf;
+//! # use abnf::{Async, BytesMut, Poll};
+//! # use abnf::parse::rule::{separated, FatalError};
+//! # struct Res;
+//! # struct E;
+//! # impl FatalError for E { }
+//! # fn rule(buf: &mut BytesMut) -> Poll<Res, E> { Ok(Async::Ready(Res)) }
+//! # fn comma(buf: &mut BytesMut) -> Poll<(), E> { Ok(Async::Ready(())) }
+//! fn comma_separated_rule(buf: &mut BytesMut) -> Poll<Vec<Res>, E> {
+//!     let mut res = Vec::new();
+//!     try_ready!(separated(buf, rule, comma, |item| {
+//!         match item {
+//!             Ok(item) => {
+//!                 res.push(item);
+//!                 Ok(Async::NotReady)
+//!             }
+//!             Err(_) if res.is_empty() => Err(E),
+//!             Err(_) => Ok(Async::Ready(()))
+//!         }
+//!     }));
+//!     Ok(Async::Ready(res))
+//! }
+//! # fn main() { }
+//! ```
+//!
+//!
+//! # Committing to a Branch
+//!
+//! `alternatives()` and friends pick their error by trying every remaining
+//! branch and keeping whichever got furthest — which is usually right, but
+//! not always. Once a branch has matched something that can *only* be that
+//! branch (a keyword, say), any later failure in it is a real error in the
+//! input, not just “this branch didn’t match”; trying the other branches
+//! only buries that error under a worse one.
+//!
+//! `commit()` lets you say so. It runs a parsing closure and, if it fails,
+//! wraps the error in `Committed` so that `is_fatal()` returns `true` for
+//! it. `group()`, `alternatives()`, and `optional()` all check this before
+//! rewinding: a fatal error skips the rewind-and-try-something-else step
+//! and is returned right away instead.
+//!
+//! ```
+//! # #[macro_use] extern crate abnf;
+//! # use abnf::{Async, BytesMut, Poll};
+//! # use abnf::parse::rule::{alternatives, commit, group, Committed, FatalError};
+//! # struct Res;
+//! # struct E;
+//! # impl FatalError for E { }
+//! # fn keyword(buf: &mut BytesMut) -> Poll<Option<()>, E> { Ok(Async::Ready(None)) }
+//! # fn body(buf: &mut BytesMut) -> Poll<Res, E> { Ok(Async::Ready(Res)) }
+//! # fn other_rule(buf: &mut BytesMut) -> Poll<Option<Res>, Committed<E>> {
+//! #     Ok(Async::Ready(None))
+//! # }
+//! fn keyword_rule(buf: &mut BytesMut) -> Poll<Option<Res>, Committed<E>> {
+//!     group(buf, |buf| {
+//!         match try_ready!(keyword(buf).map_err(Committed)) {
+//!             None => return Ok(Async::Ready(None)),
+//!             Some(_) => { }
+//!         }
+//!         Ok(Async::Ready(Some(try_ready!(commit(buf, body)))))
+//!     })
+//! }
+//!
+//! fn alt(buf: &mut BytesMut) -> Poll<Option<Res>, Committed<E>> {
+//!     alternatives(buf, &[keyword_rule, other_rule])
+//! }
+//! # fn main() { }
+//! ```
+//!
+//! Note that `Committed<E>` is unconditionally fatal — it has no
+//! recoverable state — so once it is the shared error type of an
+//! `alternatives()` set, *every* branch's `Err` is fatal, and
+//! `alternatives()`'s farthest-failure ranking (see above) never gets a
+//! chance to run: the first branch to fail wins outright instead of the
+//! one that got furthest. That is fine for `alt()` above, where each
+//! branch is already expected to either match or be a hard error. If you
+//! need some branches to fail recoverably and others to commit, don’t
+//! share `Committed<E>` as the error type; define your own enum
+//! implementing `FatalError` with both a recoverable and a fatal variant
+//! instead, and have the committing branch map into the fatal one by hand
+//! rather than using `commit()`/`Committed`.
 
 use bytes::BytesMut;
 use futures::{Async, Poll};
@@ -221,13 +441,74 @@ use futures::{Async, Poll};
 
 //------------ Combining Rules -----------------------------------------------
 
+/// An error that knows whether it can be recovered from.
+///
+/// `group()`, `alternatives()`, and `optional()` all react to a failure by
+/// rewinding the buffer and either giving up quietly or trying something
+/// else. Before doing so, they call `is_fatal()` on the error: if it
+/// returns `true`, they leave the buffer as is and bubble the error up
+/// instead, skipping the rewind-and-retry path entirely. This is what lets
+/// `commit()` turn a recoverable failure into a hard one.
+///
+/// The default implementation treats every error as recoverable, which is
+/// the previous, and still the common, behaviour.
+pub trait FatalError {
+    fn is_fatal(&self) -> bool {
+        false
+    }
+}
+
+/// Wraps an error to mark it as fatal.
+///
+/// Produced by `commit()`. `is_fatal()` always returns `true` for it —
+/// `Committed<E>` has no recoverable state — so if it ends up being the
+/// shared error type of an `alternatives()` set, every branch's `Err`
+/// becomes fatal and the farthest-failure ranking never runs (see the
+/// [module documentation] for details). Use a custom error enum
+/// implementing `FatalError` instead if you need some branches in the
+/// same set to stay recoverable.
+///
+/// [module documentation]: index.html#committing-to-a-branch
+pub struct Committed<E>(pub E);
+
+impl<E> FatalError for Committed<E> {
+    fn is_fatal(&self) -> bool {
+        true
+    }
+}
+
+/// Makes a failure non-recoverable.
+///
+/// Ordinarily, a rule failing with `Err` is recoverable: whatever
+/// `group()`, `alternatives()`, or `optional()` it is nested in will
+/// rewind the buffer and either give up quietly or try an alternative.
+/// Once you know a branch is the right one — say, after a required
+/// keyword has matched — that produces vague “nothing matched” errors
+/// instead of the actual, specific problem.
+///
+/// `commit()` runs `parse` and, if it fails, wraps the error in
+/// `Committed`, whose `is_fatal()` always returns `true`. Enclosing
+/// combinators then skip the rewind and try-something-else step and fail
+/// the whole parse with the wrapped error instead.
+pub fn commit<P, R, E>(buf: &mut BytesMut, parse: P) -> Poll<R, Committed<E>>
+           where P: FnOnce(&mut BytesMut) -> Poll<R, E> {
+    parse(buf).map_err(Committed)
+}
+
+
 /// Succeeds if parsing within `op` succeeds or rewinds.
+///
+/// If parsing fails with a fatal error (see `FatalError`), the buffer is
+/// *not* rewound and the error is returned as is, rather than being
+/// treated like any other failure.
 pub fn group<P, T, E>(buf: &mut BytesMut, parse: P) -> Poll<T, E>
-           where P: FnOnce(&mut BytesMut) -> Poll<T, E> {
+           where P: FnOnce(&mut BytesMut) -> Poll<T, E>,
+                 E: FatalError {
     let orig_buf = buf.clone();
     let res = parse(buf);
     match res {
-        Ok(Async::NotReady) | Err(_) => *buf = orig_buf,
+        Ok(Async::NotReady) => *buf = orig_buf,
+        Err(ref err) if !err.is_fatal() => *buf = orig_buf,
         _ => {}
     }
     res
@@ -245,6 +526,161 @@ pub fn opt_group<P, T, E>(buf: &mut BytesMut, parse: P) -> Poll<Option<T>, E>
 }
 
 
+/// Captures the bytes consumed by a parsing closure.
+///
+/// This works just like `group()` — a clone of the original buffer is kept
+/// while `parse` drains the buffer itself and, on `NotReady` or a non-fatal
+/// `Err`, the buffer is rewound to that clone exactly as `group()` would; a fatal error (see `FatalError`)
+/// is left as is instead, with the buffer at the failure point. On
+/// success, though, `recognize()` doesn’t return `parse`’s result at all.
+/// Instead it returns the slice of the original buffer that `parse`
+/// drained, i.e., everything it matched.
+pub fn recognize<P, R, E>(buf: &mut BytesMut, parse: P) -> Poll<BytesMut, E>
+           where P: FnOnce(&mut BytesMut) -> Poll<R, E>,
+                 E: FatalError {
+    let mut orig_buf = buf.clone();
+    match parse(buf) {
+        Ok(Async::Ready(_)) => {
+            Ok(Async::Ready(orig_buf.split_to(orig_buf.len() - buf.len())))
+        }
+        Ok(Async::NotReady) => {
+            *buf = orig_buf;
+            Ok(Async::NotReady)
+        }
+        Err(err) => {
+            if !err.is_fatal() {
+                *buf = orig_buf;
+            }
+            Err(err)
+        }
+    }
+}
+
+
+/// Tries a number of branches in order, keeping the best failure.
+///
+/// Each of `branches` is tried in turn against a fresh copy of the buffer.
+/// The first branch to return `Ready(Some(_))` wins and its result is
+/// returned right away. A branch returning `NotReady` causes the whole
+/// thing to rewind and return `NotReady`, too, since more bytes are needed
+/// before a decision can be made.
+///
+/// If a branch returns `Ready(None)` it simply didn’t match and the next
+/// branch is tried against the buffer as it was before that branch ran. If
+/// a (non-fatal) `Err`, how far it got before failing — i.e.,
+/// `orig_buf.len() - buf.len()` at the point of failure — is remembered.
+/// A fatal error (see `FatalError`) skips this bookkeeping entirely and is
+/// returned right away. Once all branches have been tried without success,
+/// the buffer is rewound and the error of whichever branch got the
+/// farthest is returned, since that is usually the most useful
+/// diagnostic. If no branch produced an error at all, `Ready(None)` is
+/// returned.
+///
+/// This ranking only tells branches apart if they leave the buffer
+/// partially drained on a recoverable `Err` — which `alternatives()`
+/// allows, since it resets the buffer to its own starting point before
+/// every branch and again once all of them fail, so a branch need not
+/// rewind itself. A branch that *does* rewind itself on a recoverable
+/// error (the usual `group()` idiom) always measures as zero progress,
+/// so among such branches the ranking degenerates to “the first error
+/// seen”.
+///
+/// See the [module documentation] for an example and the `alternatives!()`
+/// macro for branches that aren’t all the same closure type.
+///
+/// [module documentation]: index.html#alternatives-rule1--rule2
+pub fn alternatives<P, R, E>(buf: &mut BytesMut, branches: &[P])
+                             -> Poll<Option<R>, E>
+              where P: Fn(&mut BytesMut) -> Poll<Option<R>, E>,
+                    E: FatalError {
+    let orig_buf = buf.clone();
+    let mut farthest: Option<(usize, E)> = None;
+    for branch in branches {
+        *buf = orig_buf.clone();
+        match branch(buf) {
+            Ok(Async::Ready(Some(res))) => return Ok(Async::Ready(Some(res))),
+            Ok(Async::NotReady) => {
+                *buf = orig_buf;
+                return Ok(Async::NotReady)
+            }
+            Ok(Async::Ready(None)) => { }
+            Err(err) => {
+                if err.is_fatal() {
+                    return Err(err);
+                }
+                let advanced = orig_buf.len() - buf.len();
+                let replace = match farthest {
+                    Some((best, _)) => advanced > best,
+                    None => true,
+                };
+                if replace {
+                    farthest = Some((advanced, err));
+                }
+            }
+        }
+    }
+    *buf = orig_buf;
+    match farthest {
+        Some((_, err)) => Err(err),
+        None => Ok(Async::Ready(None)),
+    }
+}
+
+
+/// Like `alternatives()` but for branches of different closure types.
+///
+/// `alternatives()` needs all its branches to be values of the same type,
+/// which rules out passing a list of distinct closures (they each have
+/// their own, unnameable type). This macro takes the branches as separate
+/// arguments instead and applies the exact same farthest-failure logic,
+/// modeled after `combine`’s `choice!()`.
+///
+/// ```ignore
+/// alternatives!(buf, rule1, rule2, rule3)
+/// ```
+#[macro_export]
+macro_rules! alternatives {
+    ($buf:expr, $($branch:expr),+ $(,)*) => {{
+        use $crate::futures::Async;
+        let __orig_buf = $buf.clone();
+        let mut __farthest = None;
+        'alternatives: loop {
+            $(
+                *$buf = __orig_buf.clone();
+                match ($branch)($buf) {
+                    Ok(Async::Ready(Some(res))) => {
+                        break 'alternatives Ok(Async::Ready(Some(res)));
+                    }
+                    Ok(Async::NotReady) => {
+                        *$buf = __orig_buf;
+                        break 'alternatives Ok(Async::NotReady);
+                    }
+                    Ok(Async::Ready(None)) => { }
+                    Err(err) => {
+                        if $crate::parse::rule::FatalError::is_fatal(&err) {
+                            break 'alternatives Err(err);
+                        }
+                        let advanced = __orig_buf.len() - $buf.len();
+                        let replace = match __farthest {
+                            Some((best, _)) => advanced > best,
+                            None => true,
+                        };
+                        if replace {
+                            __farthest = Some((advanced, err));
+                        }
+                    }
+                }
+            )+
+            *$buf = __orig_buf;
+            break 'alternatives match __farthest {
+                Some((_, err)) => Err(err),
+                None => Ok(Async::Ready(None)),
+            };
+        }
+    }}
+}
+
+
 /// Repetition.
 ///
 /// This combinator is driven by two closures.
@@ -258,48 +694,254 @@ pub fn opt_group<P, T, E>(buf: &mut BytesMut, parse: P) -> Poll<Option<T>, E>
 /// next. If it returns an error, the whole repetition rewinds and results
 /// in that error. It it returns a value, the repetition is over producing
 /// this result. If it returns non-ready, another iterations is done.
+///
+/// If `parse` succeeds without draining anything from the buffer, trying
+/// again would just get the same empty match forever, so `combine` is
+/// given this last, zero-width item and then the repetition is stopped
+/// right there, via `Ok(Async::Ready(S::default()))`, instead of looping.
+/// Note that this discards whatever `combine` would have produced: `S`
+/// must be a pure sentinel (`()` in the module documentation’s examples), with the real
+/// result built up in a variable the `combine` closure captures. If
+/// `combine` needs to produce the final value itself on this path, use
+/// `repeat_range()` with `max: None` instead, which calls `combine` with
+/// `None` to ask for a final result rather than inventing one.
 pub fn repeat<P, R, E, C, S, F>(buf: &mut BytesMut, parse: P, mut combine: C)
                           -> Poll<S, F>
               where P: Fn(&mut BytesMut) -> Poll<R, E>,
-                    C: FnMut(Result<R, E>) -> Poll<S, F> {
+                    C: FnMut(Result<R, E>) -> Poll<S, F>,
+                    S: Default,
+                    F: FatalError {
     group(buf, |buf| {
         loop {
+            let before = buf.len();
             let item = try_result!(parse(buf));
+            let progressed = match item {
+                Ok(_) => buf.len() != before,
+                Err(_) => true,
+            };
             match combine(item) {
                 Ok(Async::Ready(res)) => return Ok(Async::Ready(res)),
                 Err(err) =>  return Err(err),
-                Ok(Async::NotReady) => { }
+                Ok(Async::NotReady) => {
+                    if !progressed {
+                        return Ok(Async::Ready(S::default()))
+                    }
+                }
+            }
+        }
+    })
+}
+
+
+/// Repetition bounded by a minimum and, optionally, a maximum count.
+///
+/// This is like `repeat()`, except that `parse` is attempted at most `max`
+/// times (unbounded if `max` is `None`). Once that many elements have
+/// successfully been parsed, `combine` is called with `None` instead of a
+/// parse result, indicating that nothing more is coming — just like it is
+/// called with `Some(Err(_))` when `parse` itself fails. Either way,
+/// `combine` is expected to produce a final result at that point.
+///
+/// If, once `combine` produces that final result, fewer than `min`
+/// elements were successfully parsed, the whole repetition fails with the
+/// error produced by `too_few` and rewinds, rather than succeeding with a
+/// result that is too short.
+///
+/// Just like `repeat()`, a `parse` that succeeds without draining the
+/// buffer is treated as having run out of matches: `combine` still sees
+/// that one zero-width item, but is then given `None` on the next round
+/// instead of `parse` being tried again and again forever.
+pub fn repeat_range<P, R, E, C, S, F, D>(buf: &mut BytesMut,
+                                         min: usize, max: Option<usize>,
+                                         parse: P, mut combine: C,
+                                         too_few: D)
+                                         -> Poll<S, F>
+              where P: Fn(&mut BytesMut) -> Poll<R, E>,
+                    C: FnMut(Option<Result<R, E>>) -> Poll<S, F>,
+                    D: FnOnce() -> F,
+                    F: FatalError {
+    group(buf, |buf| {
+        let mut count = 0;
+        let mut stop = false;
+        loop {
+            let item = if stop || max.map_or(false, |max| count >= max) {
+                None
+            }
+            else {
+                let before = buf.len();
+                let item = try_result!(parse(buf));
+                if item.is_ok() {
+                    count += 1;
+                    // A zero-width match would otherwise be parsed again
+                    // and again forever, so treat it as having exhausted
+                    // `parse`, same as hitting `max` or a real failure.
+                    if buf.len() == before {
+                        stop = true;
+                    }
+                }
+                Some(item)
+            };
+            let exhausted = item.is_none();
+            match combine(item) {
+                Ok(Async::Ready(res)) => {
+                    return if count < min {
+                        Err(too_few())
+                    }
+                    else {
+                        Ok(Async::Ready(res))
+                    }
+                }
+                Err(err) => return Err(err),
+                Ok(Async::NotReady) => {
+                    // `combine` asked for more even though nothing more
+                    // is available. There is nothing we can do but stop.
+                    if exhausted {
+                        return Err(too_few())
+                    }
+                }
             }
         }
     })
 }
 
 
+/// `<n>Rule`: parse exactly `n` elements.
+///
+/// A thin wrapper around `repeat_range()` with `min` and `max` both set to
+/// `n`.
+pub fn exactly<P, R, E, C, S, F, D>(buf: &mut BytesMut, n: usize,
+                                    parse: P, combine: C, too_few: D)
+                                    -> Poll<S, F>
+              where P: Fn(&mut BytesMut) -> Poll<R, E>,
+                    C: FnMut(Option<Result<R, E>>) -> Poll<S, F>,
+                    D: FnOnce() -> F,
+                    F: FatalError {
+    repeat_range(buf, n, Some(n), parse, combine, too_few)
+}
+
+
 /// Repeat at least once.
 ///
 /// This is like `repeat()`, but if `parse` fails already on the first time,
 /// `combine` isn’t called at all but rather `empty`.
+///
+/// Since there is no accumulated result to fall back on before the first
+/// element has been parsed, a `parse` that succeeds without draining the
+/// buffer — on the first element or any later one — is not treated as “we
+/// are done”, like in `repeat()`, but as a genuine, distinct error,
+/// produced by passing `E::default()` to `error`, the same closure that
+/// receives a real parse failure of the first element. (A real parse
+/// failure of a later element goes to `combine`, as in `repeat()`.)
 pub fn at_least_once<P, R, E, C, S, F, D>(buf: &mut BytesMut,
                                           parse: P, mut combine: C, error: D)
                                           -> Poll<S, F>
                      where P: Fn(&mut BytesMut) -> Poll<R, E>,
                            C: FnMut(Result<R, E>) -> Poll<S, F>,
-                           D: FnOnce(E) -> F {
+                           D: FnOnce(E) -> F,
+                           E: Default,
+                           F: FatalError {
     group(buf, |buf| {
+        let before = buf.len();
         match try_result!(parse(buf)) {
             Err(err) => return Err(error(err)),
-            Ok(item) => match combine(Ok(item)) {
-                Ok(Async::Ready(res)) => return Ok(Async::Ready(res)),
-                Err(err) => return Err(err),
-                Ok(Async::NotReady) => { }
+            Ok(item) => {
+                let progressed = buf.len() != before;
+                match combine(Ok(item)) {
+                    Ok(Async::Ready(res)) => return Ok(Async::Ready(res)),
+                    Err(err) => return Err(err),
+                    Ok(Async::NotReady) => {
+                        if !progressed {
+                            return Err(error(E::default()))
+                        }
+                    }
+                }
             }
         }
         loop {
+            let before = buf.len();
             let item = try_result!(parse(buf));
+            let progressed = match item {
+                Ok(_) => buf.len() != before,
+                Err(_) => true,
+            };
             match combine(item) {
                 Ok(Async::Ready(res)) => return Ok(Async::Ready(res)),
                 Err(err) =>  return Err(err),
-                Ok(Async::NotReady) => { }
+                Ok(Async::NotReady) => {
+                    if !progressed {
+                        return Err(error(E::default()))
+                    }
+                }
+            }
+        }
+    })
+}
+
+
+/// A list of elements separated by some separator.
+///
+/// One `parse_item` is parsed, followed by as many `parse_sep`/`parse_item`
+/// pairs as there are. Just like in `repeat()`, each successfully parsed
+/// item is passed to `combine` as `Ok(_)`, which decides whether to keep
+/// going or to finish up.
+///
+/// The list ends either because `parse_sep` fails to match (there simply
+/// is no more separator) or because a separator matched but the following
+/// `parse_item` didn’t (a trailing separator). In the latter case, the
+/// separator is rewound so it isn’t consumed. Either way, `combine` is
+/// given the `Err(_)` of whichever closure failed, exactly like `repeat()`
+/// hands it the error from a failing `parse`, so it can finish up the same
+/// way. A failure of the very first `parse_item` is handed to `combine` as
+/// `Err(_)` too; `separated()` does not by itself require at least one
+/// element, so an empty list is accepted unless `combine` returns an error.
+///
+/// Just like `repeat()`, if a separator/item pair is matched without
+/// draining anything from the buffer, trying again would loop forever, so
+/// `separated()` stops right there instead, via `Ok(Async::Ready(S::default()))`
+/// rather than whatever `combine` would have produced — so, as with
+/// `repeat()`, `S` should be a pure sentinel type with the real result
+/// accumulated in a variable `combine` captures.
+pub fn separated<PI, PS, R, Rs, E, C, S, F>(buf: &mut BytesMut,
+                                            parse_item: PI, parse_sep: PS,
+                                            mut combine: C) -> Poll<S, F>
+              where PI: Fn(&mut BytesMut) -> Poll<R, E>,
+                    PS: Fn(&mut BytesMut) -> Poll<Rs, E>,
+                    C: FnMut(Result<R, E>) -> Poll<S, F>,
+                    S: Default,
+                    F: FatalError {
+    group(buf, |buf| {
+        match combine(try_result!(parse_item(buf))) {
+            Ok(Async::Ready(res)) => return Ok(Async::Ready(res)),
+            Err(err) => return Err(err),
+            Ok(Async::NotReady) => { }
+        }
+        loop {
+            let before = buf.len();
+            let before_sep = buf.clone();
+            let item = match try_result!(parse_sep(buf)) {
+                Err(err) => Err(err),
+                Ok(_) => {
+                    match try_result!(parse_item(buf)) {
+                        Ok(item) => Ok(item),
+                        Err(err) => {
+                            *buf = before_sep;
+                            Err(err)
+                        }
+                    }
+                }
+            };
+            let progressed = match item {
+                Ok(_) => buf.len() != before,
+                Err(_) => true,
+            };
+            match combine(item) {
+                Ok(Async::Ready(res)) => return Ok(Async::Ready(res)),
+                Err(err) => return Err(err),
+                Ok(Async::NotReady) => {
+                    if !progressed {
+                        return Ok(Async::Ready(S::default()))
+                    }
+                }
             }
         }
     })
@@ -307,12 +949,24 @@ pub fn at_least_once<P, R, E, C, S, F, D>(buf: &mut BytesMut,
 
 
 /// An optional rule.
-pub fn optional<P, R, E, F>(buf: &mut BytesMut, parse: P) -> Poll<Option<R>, F>
-                where P: FnOnce(&mut BytesMut) -> Poll<R, E> {
+///
+/// A fatal error (see `FatalError`) is not turned into `None` but
+/// propagated as is, since it means the enclosing parse should fail
+/// outright rather than simply treat `parse` as not having matched.
+pub fn optional<P, R, E>(buf: &mut BytesMut, parse: P) -> Poll<Option<R>, E>
+                where P: FnOnce(&mut BytesMut) -> Poll<R, E>,
+                      E: FatalError {
     match parse(buf) {
         Ok(Async::NotReady) => Ok(Async::NotReady),
         Ok(Async::Ready(some)) => Ok(Async::Ready(Some(some))),
-        Err(_) => Ok(Async::Ready(None))
+        Err(err) => {
+            if err.is_fatal() {
+                Err(err)
+            }
+            else {
+                Ok(Async::Ready(None))
+            }
+        }
     }
 }
 